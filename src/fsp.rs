@@ -0,0 +1,306 @@
+//! Finite State Projection (FSP): an exact solver for the marginal
+//! probability distributions of a reaction network, obtained by
+//! truncating the (otherwise infinite) state space of the chemical
+//! master equation (CME) to a finite hyper-rectangle and integrating
+//! the resulting linear ODE in time.
+//!
+//! This complements the stochastic sampler in [`crate::gillespie`] with
+//! an exact method, practical for low-dimensional systems (a handful of
+//! species with small truncation bounds).
+
+use crate::gillespie::{Gillespie, Rate};
+
+/// The time step used by the explicit RK4 integration of the CME.
+/// Chosen well below typical propensity scales; systems with much
+/// larger propensities may need to pass a smaller `dt` explicitly to
+/// [`solve_with_step`], which checks it against
+/// [`STABILITY_SAFETY_FACTOR`] rather than silently integrating an
+/// unstable step.
+const DEFAULT_DT: f64 = 1e-3;
+
+/// Conservative bound on `dt` times the fastest total outflow rate out
+/// of any state (the generator's largest-magnitude diagonal entry):
+/// exceeding it almost certainly means the explicit RK4 integration in
+/// [`StateSpace::integrate`] is numerically unstable for this system.
+/// This is a cheap heuristic, not the exact RK4 stability region for
+/// every generator, but it catches the common failure mode of a `dt`
+/// chosen without regard to the model's propensity scale.
+const STABILITY_SAFETY_FACTOR: f64 = 1.0;
+
+/// A truncated state space: a hyper-rectangle `0..=bounds[i]` for each
+/// species `i`, plus one absorbing "sink" state collecting all the
+/// probability mass that would leave the truncation.
+pub struct StateSpace {
+    bounds: Vec<usize>,
+    strides: Vec<usize>,
+    n_states: usize,
+}
+
+impl StateSpace {
+    /// Builds the state space `0..=bounds[i]` for each species `i`.
+    pub fn new(bounds: Vec<usize>) -> Self {
+        let mut strides = vec![1; bounds.len()];
+        for i in 1..bounds.len() {
+            strides[i] = strides[i - 1] * (bounds[i - 1] + 1);
+        }
+        let n_states = bounds.iter().map(|&b| b + 1).product::<usize>().max(1);
+        StateSpace {
+            bounds,
+            strides,
+            n_states,
+        }
+    }
+
+    /// Number of species described by this state space.
+    fn n_species(&self) -> usize {
+        self.bounds.len()
+    }
+
+    /// Number of states inside the truncation (not counting the sink).
+    pub fn n_states(&self) -> usize {
+        self.n_states
+    }
+
+    /// The index of the absorbing sink state.
+    pub fn sink(&self) -> usize {
+        self.n_states
+    }
+
+    /// Linear index of state `x`, or `None` if `x` falls outside the
+    /// truncation.
+    fn index(&self, x: &[isize]) -> Option<usize> {
+        let mut idx = 0;
+        for i in 0..self.n_species() {
+            if x[i] < 0 || x[i] as usize > self.bounds[i] {
+                return None;
+            }
+            idx += x[i] as usize * self.strides[i];
+        }
+        Some(idx)
+    }
+
+    /// State corresponding to linear index `idx`.
+    fn state(&self, idx: usize) -> Vec<isize> {
+        let mut x = vec![0; self.n_species()];
+        let mut rest = idx;
+        for i in (0..self.n_species()).rev() {
+            x[i] = (rest / self.strides[i]) as isize;
+            rest %= self.strides[i];
+        }
+        x
+    }
+
+    /// Assembles the sparse CME generator `A` over the truncated state
+    /// space plus the sink state, as a list of `(row, col, value)`
+    /// entries: `A[y, x] = a_j(x)` for every reaction `j` mapping state
+    /// `x` to `y` (or to the sink, if `y` falls outside the
+    /// truncation), and the diagonal `A[x, x] = -sum_j a_j(x)`.
+    ///
+    /// The generator is assembled once and reused for the whole
+    /// integration, so it is only valid for time-independent rates;
+    /// [`solve_with_step`] rejects a time-varying `Rate` before calling
+    /// this rather than silently evaluating it at `t = 0` and treating
+    /// it as constant throughout.
+    fn build_generator(
+        &self,
+        rates: &[crate::gillespie::Rate],
+        actions: &[Vec<isize>],
+    ) -> Vec<(usize, usize, f64)> {
+        let mut entries = Vec::new();
+        let mut diagonal = vec![0.; self.n_states + 1];
+        for x_idx in 0..self.n_states {
+            let x = self.state(x_idx);
+            for (rate, action) in rates.iter().zip(actions) {
+                let a = rate.evaluate(&x, 0.);
+                if a <= 0. {
+                    continue;
+                }
+                diagonal[x_idx] -= a;
+                let y: Vec<isize> = x.iter().zip(action).map(|(&xi, &ai)| xi + ai).collect();
+                let y_idx = self.index(&y).unwrap_or(self.sink());
+                entries.push((y_idx, x_idx, a));
+            }
+        }
+        for (i, &d) in diagonal.iter().enumerate() {
+            if d != 0. {
+                entries.push((i, i, d));
+            }
+        }
+        entries
+    }
+
+    /// Integrates `dp/dt = A p` from `p0` to `tmax` with an explicit
+    /// RK4 scheme of step size `dt`, and returns the resulting
+    /// distribution over the truncated state space plus the sink.
+    pub fn integrate(
+        &self,
+        generator: &[(usize, usize, f64)],
+        mut p: Vec<f64>,
+        tmax: f64,
+        dt: f64,
+    ) -> Vec<f64> {
+        let apply = |p: &[f64]| -> Vec<f64> {
+            let mut dp = vec![0.; p.len()];
+            for &(row, col, value) in generator {
+                dp[row] += value * p[col];
+            }
+            dp
+        };
+        let mut t = 0.;
+        while t < tmax {
+            let h = dt.min(tmax - t);
+            let k1 = apply(&p);
+            let p2: Vec<f64> = p
+                .iter()
+                .zip(&k1)
+                .map(|(&pi, &k)| pi + 0.5 * h * k)
+                .collect();
+            let k2 = apply(&p2);
+            let p3: Vec<f64> = p
+                .iter()
+                .zip(&k2)
+                .map(|(&pi, &k)| pi + 0.5 * h * k)
+                .collect();
+            let k3 = apply(&p3);
+            let p4: Vec<f64> = p.iter().zip(&k3).map(|(&pi, &k)| pi + h * k).collect();
+            let k4 = apply(&p4);
+            for i in 0..p.len() {
+                p[i] += h / 6. * (k1[i] + 2. * k2[i] + 2. * k3[i] + k4[i]);
+            }
+            t += h;
+        }
+        p
+    }
+}
+
+/// The result of a finite state projection: the exact marginal
+/// distribution of each species within its truncation bound, and the
+/// probability mass that has escaped the truncation by `tmax`.
+pub struct FspResult {
+    /// `marginals[s][n]` is the probability that species `s` has
+    /// population `n`, for `n` in `0..=bounds[s]`.
+    pub marginals: Vec<Vec<f64>>,
+    /// Probability mass that left the truncated state space by `tmax`.
+    /// A large value means `bounds` should be tightened.
+    pub sink_probability: f64,
+}
+
+/// The generator's largest-magnitude diagonal entry: the fastest total
+/// outflow rate out of any single state, used by [`solve_with_step`] to
+/// reject a `dt` too large to integrate stably.
+fn max_outflow_rate(generator: &[(usize, usize, f64)]) -> f64 {
+    generator
+        .iter()
+        .filter(|&&(row, col, _)| row == col)
+        .map(|&(_, _, value)| value.abs())
+        .fold(0., f64::max)
+}
+
+/// Solves the chemical master equation for `problem` exactly over the
+/// truncated state space `0..=bounds[s]` for each species `s`, from
+/// `problem`'s current state to `tmax`.
+pub fn solve(problem: &Gillespie, bounds: Vec<usize>, tmax: f64) -> FspResult {
+    solve_with_step(problem, bounds, tmax, DEFAULT_DT)
+}
+
+/// Like [`solve`], but with an explicit integration step `dt`; smaller
+/// steps are needed for systems with large propensities.
+///
+/// # Panics
+///
+/// Panics if any of `problem`'s rates is time-varying (the generator is
+/// assembled once from propensities at `t = 0` and reused for the whole
+/// integration, so it cannot represent a non-autonomous rate), or if
+/// `dt` is too large relative to the system's fastest rate for the
+/// explicit RK4 integration to be stable (see
+/// [`STABILITY_SAFETY_FACTOR`]).
+pub fn solve_with_step(problem: &Gillespie, bounds: Vec<usize>, tmax: f64, dt: f64) -> FspResult {
+    assert!(
+        !problem.rates().iter().any(Rate::is_time_varying),
+        "fsp::solve does not support time-varying rates: the generator is built once from \
+         propensities at t = 0 and reused for the whole integration, which would silently \
+         misrepresent a non-autonomous system; sample trajectories with \
+         Gillespie::advance_until instead"
+    );
+    let space = StateSpace::new(bounds.clone());
+    let generator = space.build_generator(problem.rates(), problem.actions());
+    let max_outflow = max_outflow_rate(&generator);
+    assert!(
+        dt * max_outflow <= STABILITY_SAFETY_FACTOR,
+        "dt = {dt} is too large for this system's fastest rate (outflow {max_outflow} per unit \
+         time): the explicit RK4 integration would be numerically unstable; pass dt <= {} to \
+         solve_with_step",
+        STABILITY_SAFETY_FACTOR / max_outflow
+    );
+    let mut p0 = vec![0.; space.n_states() + 1];
+    match space.index(problem.get_state()) {
+        Some(idx) => p0[idx] = 1.,
+        None => p0[space.sink()] = 1.,
+    }
+    let p = space.integrate(&generator, p0, tmax - problem.get_time(), dt);
+
+    let n_species = bounds.len();
+    let mut marginals: Vec<Vec<f64>> = bounds.iter().map(|&b| vec![0.; b + 1]).collect();
+    for x_idx in 0..space.n_states() {
+        if p[x_idx] == 0. {
+            continue;
+        }
+        let x = space.state(x_idx);
+        for s in 0..n_species {
+            marginals[s][x[s] as usize] += p[x_idx];
+        }
+    }
+    FspResult {
+        marginals,
+        sink_probability: p[space.sink()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gillespie::Rate;
+
+    /// The generator `A` describes a (sub-)stochastic Markov process, so
+    /// each column must sum to ~0: probability flowing out of a state
+    /// along its off-diagonal entries is exactly cancelled by the
+    /// diagonal entry.
+    #[test]
+    fn generator_columns_conserve_probability() {
+        let mut g = Gillespie::new_with_seed([10, 0], 0);
+        g.add_reaction(Rate::lma(1.0, [0]), [-1, 1]);
+        g.add_reaction(Rate::lma(0.5, [1]), [1, -1]);
+        let space = StateSpace::new(vec![10, 10]);
+        let generator = space.build_generator(g.rates(), g.actions());
+        let mut column_sums = vec![0.; space.n_states() + 1];
+        for &(_row, col, value) in &generator {
+            column_sums[col] += value;
+        }
+        for (col, &sum) in column_sums.iter().enumerate() {
+            assert!(
+                sum.abs() < 1e-9,
+                "column {col} of the generator sums to {sum}, not 0"
+            );
+        }
+    }
+
+    /// Total probability (truncation plus sink) must remain 1 throughout
+    /// the integration, and a truncation generous enough to contain the
+    /// whole reachable state space must keep the sink empty.
+    #[test]
+    fn sink_probability_is_zero_within_a_generous_truncation() {
+        let mut g = Gillespie::new_with_seed([5, 0], 0);
+        g.add_reaction(Rate::lma(1.0, [0]), [-1, 1]);
+        let result = solve(&g, vec![5, 5], 10.0);
+        assert!(
+            result.sink_probability < 1e-6,
+            "sink probability {} should be ~0 within a truncation covering the whole state space",
+            result.sink_probability
+        );
+        let total: f64 = result.marginals[1].iter().sum::<f64>() + result.sink_probability;
+        assert!(
+            (total - 1.0).abs() < 1e-6,
+            "total probability {total} should be ~1"
+        );
+    }
+}
@@ -0,0 +1,1089 @@
+//! Function-based API to define and simulate reaction networks at run
+//! time, without requiring a Rust compilation.
+//!
+//! This is the engine that underlies the Python bindings, but it can
+//! also be used directly from Rust when the reaction network is not
+//! known at compile time.
+
+use std::sync::Arc;
+
+use rand::prelude::*;
+use rand_distr::{Exp1, Poisson, Uniform};
+use rand_pcg::Pcg64;
+
+/// Target relative change in each species' population tolerated by a
+/// tau-leap step, in the automatic step-size selection of
+/// [`Gillespie::advance_until_tau_leap`] (see [`Gillespie::select_tau`]).
+/// The change in the propensities themselves is not bounded directly.
+const TAU_LEAP_EPSILON: f64 = 0.03;
+
+/// Lookahead window used to locally bound the propensity of a
+/// continuous time-varying rate ([`Schedule::Function`]) for thinning,
+/// when no upcoming breakpoint already limits the window.
+const THINNING_WINDOW: f64 = 1.0;
+
+/// A reaction is considered critical, and thus excluded from leaping and
+/// fired exactly instead, when fewer than this many firings would
+/// exhaust one of its reactants.
+const TAU_LEAP_N_C: isize = 10;
+
+/// Number of times a rejected tau-leap (that would send a population
+/// negative) is halved before giving up and falling back to a single
+/// exact SSA step.
+const TAU_LEAP_MAX_RETRIES: u32 = 20;
+
+/// A time-varying scalar factor, used by `Rate::TimeVarying` to model
+/// externally driven inputs (induction pulses, temperature shifts,
+/// etc.) on top of an otherwise time-independent rate law.
+#[derive(Clone)]
+pub enum Schedule {
+    /// An arbitrary function of time, together with a caller-supplied
+    /// `bound(t, window)` giving a conservative upper bound on `value`
+    /// over `[t, t + window]`.  A bound cannot be derived automatically
+    /// for an arbitrary closure (any transient narrower than a sampling
+    /// gap could be missed), and an under-bound would silently break
+    /// the exactness of thinning in [`Gillespie::_advance_one_reaction`]
+    /// — so the caller must supply one, e.g. from the closed form of
+    /// the function, or use [`Schedule::Piecewise`] if no such bound is
+    /// available.
+    Function {
+        value: Arc<dyn Fn(f64) -> f64 + Send + Sync>,
+        bound: Arc<dyn Fn(f64, f64) -> f64 + Send + Sync>,
+    },
+    /// A piecewise-constant schedule of `(t, value)` breakpoints: the
+    /// value is `0` before the first breakpoint, and holds at each
+    /// breakpoint's value until the next one.  Breakpoints must be
+    /// sorted by `t`.
+    Piecewise(Vec<(f64, f64)>),
+}
+
+impl Schedule {
+    /// Builds a continuous schedule from `value` and a conservative
+    /// `bound(t, window)` over `[t, t + window]`; see [`Schedule::Function`].
+    pub fn function(
+        value: impl Fn(f64) -> f64 + Send + Sync + 'static,
+        bound: impl Fn(f64, f64) -> f64 + Send + Sync + 'static,
+    ) -> Schedule {
+        Schedule::Function {
+            value: Arc::new(value),
+            bound: Arc::new(bound),
+        }
+    }
+
+    /// The value of the schedule at time `t`.
+    fn value(&self, t: f64) -> f64 {
+        match self {
+            Schedule::Function { value, .. } => value(t),
+            Schedule::Piecewise(breakpoints) => breakpoints
+                .iter()
+                .rev()
+                .find(|&&(bt, _)| bt <= t)
+                .map_or(0., |&(_, v)| v),
+        }
+    }
+
+    /// The next breakpoint strictly after `t`, if this is a piecewise
+    /// schedule; `None` for a continuous function, or if `t` is at or
+    /// after the last breakpoint.
+    fn next_breakpoint(&self, t: f64) -> Option<f64> {
+        match self {
+            Schedule::Function { .. } => None,
+            Schedule::Piecewise(breakpoints) => {
+                breakpoints.iter().map(|&(bt, _)| bt).find(|&bt| bt > t)
+            }
+        }
+    }
+
+    /// A conservative upper bound on `value` over `[t, t + window]`,
+    /// used by [`Rate::propensity_bound`] to keep thinning exact: exact
+    /// for a piecewise schedule (constant over the window by
+    /// construction), and the caller-supplied bound for a continuous
+    /// function.
+    fn bound(&self, t: f64, window: f64) -> f64 {
+        match self {
+            Schedule::Piecewise(_) => self.value(t),
+            Schedule::Function { bound, .. } => bound(t, window),
+        }
+    }
+}
+
+/// A reaction propensity (rate law).
+///
+/// The law of mass action (`Rate::Lma`) is the default, but arbitrary
+/// non-mass-action terms (Hill, Michaelis-Menten) are also supported,
+/// and terms can be composed together with `Rate::Product` to express
+/// e.g. a mass-action reaction modulated by a regulator.  `Rate::TimeVarying`
+/// additionally modulates a factor by an externally driven [`Schedule`],
+/// for non-autonomous systems (induction pulses, temperature shifts, ...).
+#[derive(Clone)]
+pub enum Rate {
+    /// Law of mass action: `rate` times the combinatorial factor of
+    /// the stoichiometric coefficients `species`.
+    Lma(f64, Vec<isize>),
+    /// Hill term `k * x^n / (K^n + x^n)` (activation) or
+    /// `k * K^n / (K^n + x^n)` (repression), where `x` is the
+    /// population of `species`.
+    Hill {
+        k: f64,
+        species: usize,
+        n: f64,
+        k_half: f64,
+        repressing: bool,
+    },
+    /// Michaelis-Menten term `v * x / (k + x)`, where `x` is the
+    /// population of `species`.
+    MichaelisMenten { v: f64, species: usize, k: f64 },
+    /// Product of several propensity terms.
+    Product(Vec<Rate>),
+    /// `factor`'s propensity modulated by a time-varying `schedule`,
+    /// for non-autonomous systems.
+    TimeVarying(Schedule, Box<Rate>),
+}
+
+impl std::fmt::Debug for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Schedule::Function { .. } => f.write_str("Function(..)"),
+            Schedule::Piecewise(breakpoints) => {
+                f.debug_tuple("Piecewise").field(breakpoints).finish()
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for Rate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rate::Lma(rate, species) => f.debug_tuple("Lma").field(rate).field(species).finish(),
+            Rate::Hill {
+                k,
+                species,
+                n,
+                k_half,
+                repressing,
+            } => f
+                .debug_struct("Hill")
+                .field("k", k)
+                .field("species", species)
+                .field("n", n)
+                .field("k_half", k_half)
+                .field("repressing", repressing)
+                .finish(),
+            Rate::MichaelisMenten { v, species, k } => f
+                .debug_struct("MichaelisMenten")
+                .field("v", v)
+                .field("species", species)
+                .field("k", k)
+                .finish(),
+            Rate::Product(factors) => f.debug_tuple("Product").field(factors).finish(),
+            Rate::TimeVarying(schedule, factor) => f
+                .debug_tuple("TimeVarying")
+                .field(schedule)
+                .field(factor)
+                .finish(),
+        }
+    }
+}
+
+impl Rate {
+    /// Builds a law of mass action propensity with rate constant
+    /// `rate`, depending on the species in `species` (`species[i]` is
+    /// the stoichiometric coefficient of species `i` in the reactants
+    /// of the reaction).
+    pub fn lma(rate: f64, species: impl Into<Vec<isize>>) -> Rate {
+        Rate::Lma(rate, species.into())
+    }
+
+    /// Builds a Hill activation term `k * x^n / (k_half^n + x^n)`,
+    /// where `x` is the population of species `species`.
+    pub fn hill_activation(k: f64, species: usize, n: f64, k_half: f64) -> Rate {
+        Rate::Hill {
+            k,
+            species,
+            n,
+            k_half,
+            repressing: false,
+        }
+    }
+
+    /// Builds a Hill repression term `k * k_half^n / (k_half^n + x^n)`,
+    /// where `x` is the population of species `species`.
+    pub fn hill_repression(k: f64, species: usize, n: f64, k_half: f64) -> Rate {
+        Rate::Hill {
+            k,
+            species,
+            n,
+            k_half,
+            repressing: true,
+        }
+    }
+
+    /// Builds a Michaelis-Menten term `v * x / (k + x)`, where `x` is
+    /// the population of species `species`.
+    pub fn michaelis_menten(v: f64, species: usize, k: f64) -> Rate {
+        Rate::MichaelisMenten { v, species, k }
+    }
+
+    /// Builds the product of several propensity terms, e.g. a mass
+    /// action reaction modulated by a Hill term.
+    pub fn product(factors: impl Into<Vec<Rate>>) -> Rate {
+        Rate::Product(factors.into())
+    }
+
+    /// Modulates `factor` by a time-varying `schedule`, for
+    /// non-autonomous systems (induction pulses, temperature shifts,
+    /// externally driven inputs, ...).
+    pub fn time_varying(schedule: Schedule, factor: Rate) -> Rate {
+        Rate::TimeVarying(schedule, Box::new(factor))
+    }
+
+    /// Evaluates the propensity of this rate law given the current
+    /// populations `x` at time `t`.  Terms that do not depend on time
+    /// ignore `t`.
+    pub(crate) fn evaluate(&self, x: &[isize], t: f64) -> f64 {
+        match self {
+            Rate::Lma(rate, species) => {
+                let mut combinations = *rate;
+                for (&xi, &ni) in x.iter().zip(species.iter()) {
+                    for k in 0..ni {
+                        combinations *= (xi - k) as f64;
+                    }
+                }
+                combinations
+            }
+            Rate::Hill {
+                k,
+                species,
+                n,
+                k_half,
+                repressing,
+            } => {
+                let xn = (x[*species] as f64).powf(*n);
+                let kn = k_half.powf(*n);
+                if *repressing {
+                    k * kn / (kn + xn)
+                } else {
+                    k * xn / (kn + xn)
+                }
+            }
+            Rate::MichaelisMenten { v, species, k } => {
+                let xi = x[*species] as f64;
+                v * xi / (k + xi)
+            }
+            Rate::Product(factors) => factors.iter().map(|factor| factor.evaluate(x, t)).product(),
+            Rate::TimeVarying(schedule, factor) => schedule.value(t) * factor.evaluate(x, t),
+        }
+    }
+
+    /// Returns the indices of the species that this rate law depends
+    /// on.
+    fn dependencies(&self) -> Vec<usize> {
+        match self {
+            Rate::Lma(_, species) => species
+                .iter()
+                .enumerate()
+                .filter(|&(_, &n)| n != 0)
+                .map(|(i, _)| i)
+                .collect(),
+            Rate::Hill { species, .. } | Rate::MichaelisMenten { species, .. } => vec![*species],
+            Rate::Product(factors) => factors.iter().flat_map(Rate::dependencies).collect(),
+            Rate::TimeVarying(_, factor) => factor.dependencies(),
+        }
+    }
+
+    /// Whether this rate law (recursively) depends on time.
+    pub(crate) fn is_time_varying(&self) -> bool {
+        match self {
+            Rate::Lma(..) | Rate::Hill { .. } | Rate::MichaelisMenten { .. } => false,
+            Rate::Product(factors) => factors.iter().any(Rate::is_time_varying),
+            Rate::TimeVarying(..) => true,
+        }
+    }
+
+    /// The next time strictly after `t` at which this rate law's value
+    /// may jump discontinuously, from a [`Schedule::Piecewise`]
+    /// breakpoint; `None` if it varies continuously or not at all.
+    fn next_breakpoint(&self, t: f64) -> Option<f64> {
+        match self {
+            Rate::Lma(..) | Rate::Hill { .. } | Rate::MichaelisMenten { .. } => None,
+            Rate::Product(factors) => factors
+                .iter()
+                .filter_map(|factor| factor.next_breakpoint(t))
+                .fold(None, |acc, bp| Some(acc.map_or(bp, |a: f64| a.min(bp)))),
+            Rate::TimeVarying(schedule, factor) => {
+                let own = schedule.next_breakpoint(t);
+                let nested = factor.next_breakpoint(t);
+                match (own, nested) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                }
+            }
+        }
+    }
+
+    /// A conservative upper bound on [`Rate::evaluate`]`(x, _)` over
+    /// `[t, horizon]`, used to drive thinning in
+    /// [`Gillespie::_advance_one_reaction`]: exact for time-independent
+    /// terms, and widened by [`Schedule::bound`] for each time-varying
+    /// factor.
+    fn propensity_bound(&self, x: &[isize], t: f64, horizon: f64) -> f64 {
+        match self {
+            Rate::Lma(..) | Rate::Hill { .. } | Rate::MichaelisMenten { .. } => self.evaluate(x, t),
+            Rate::Product(factors) => factors
+                .iter()
+                .map(|factor| factor.propensity_bound(x, t, horizon))
+                .product(),
+            Rate::TimeVarying(schedule, factor) => {
+                schedule.bound(t, horizon - t) * factor.propensity_bound(x, t, horizon)
+            }
+        }
+    }
+}
+
+/// The SSA algorithm used to advance the simulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Gillespie's original direct method: at each step, all
+    /// propensities are recomputed and a single exponential waiting
+    /// time is drawn.
+    Direct,
+    /// The Next Reaction Method (Gibson and Bruck, 2000): maintains an
+    /// indexed priority queue of putative firing times and only
+    /// recomputes the propensities that actually change at each step.
+    /// More efficient than the direct method for large, sparsely
+    /// coupled reaction networks.
+    NextReaction,
+}
+
+impl Default for Algorithm {
+    fn default() -> Self {
+        Algorithm::Direct
+    }
+}
+
+/// The dependency graph between reactions, used by the Next Reaction
+/// Method: `graph[i]` lists the reactions whose propensity may change
+/// when reaction `i` fires.
+struct DependencyGraph {
+    graph: Vec<Vec<usize>>,
+}
+
+impl DependencyGraph {
+    /// Builds the dependency graph from the reactions' actions and
+    /// rates: reaction `j` depends on reaction `i` iff firing `i`
+    /// changes the population of at least one species that `j`'s rate
+    /// depends on.
+    fn build(rates: &[Rate], actions: &[Vec<isize>]) -> Self {
+        let deps: Vec<Vec<usize>> = rates.iter().map(|r| r.dependencies()).collect();
+        let mut graph = vec![Vec::new(); rates.len()];
+        for i in 0..rates.len() {
+            let changed: Vec<usize> = actions[i]
+                .iter()
+                .enumerate()
+                .filter(|&(_, &a)| a != 0)
+                .map(|(s, _)| s)
+                .collect();
+            for j in 0..rates.len() {
+                if deps[j].iter().any(|s| changed.contains(s)) {
+                    graph[i].push(j);
+                }
+            }
+        }
+        DependencyGraph { graph }
+    }
+}
+
+/// State of the Next Reaction Method: for each reaction, its next
+/// putative absolute firing time, stored in an indexed binary min-heap
+/// (keyed by `tau`) so that the next reaction to fire is found in O(1)
+/// and an update after a firing costs O(log n) per affected reaction,
+/// rather than rescanning every reaction's firing time each step.
+struct NextReactionState {
+    graph: DependencyGraph,
+    /// `tau[i]` is the next putative firing time of reaction `i`.
+    tau: Vec<f64>,
+    /// `a[i]` is the last propensity of reaction `i` that `tau[i]` was
+    /// computed from.  Caching it here is what lets [`Self::update`]
+    /// only recompute the propensities of `mu` and its dependents
+    /// ([`DependencyGraph::graph`]) after a firing, instead of every
+    /// reaction, which is the whole point of the Next Reaction Method
+    /// over the direct method on a sparse network.
+    a: Vec<f64>,
+    /// `heap[p]` is the reaction index stored at heap position `p`;
+    /// `heap[0]` is always the reaction with the smallest `tau`.
+    heap: Vec<usize>,
+    /// `position[i]` is the heap position of reaction `i`, the inverse
+    /// of `heap`, so that a reaction's entry can be found and repaired
+    /// in `heap` without scanning it.
+    position: Vec<usize>,
+}
+
+impl NextReactionState {
+    fn new(rates: &[Rate], actions: &[Vec<isize>]) -> Self {
+        NextReactionState {
+            graph: DependencyGraph::build(rates, actions),
+            tau: Vec::new(),
+            a: Vec::new(),
+            heap: Vec::new(),
+            position: Vec::new(),
+        }
+    }
+
+    /// (Re-)initializes the putative firing times at the current time
+    /// `t`, given the current propensities `a`, and heapifies them.
+    fn init(&mut self, t: f64, a: &[f64], rng: &mut Pcg64) {
+        self.tau = a.iter().map(|&ai| Self::draw(t, ai, rng)).collect();
+        self.a = a.to_vec();
+        let n = self.tau.len();
+        self.heap = (0..n).collect();
+        self.position = (0..n).collect();
+        for pos in (0..n / 2).rev() {
+            self.sift_down(pos);
+        }
+    }
+
+    /// Draws a putative absolute firing time `t + (1/a) ln(1/r)` for a
+    /// propensity `a`, using `+inf` when the reaction cannot fire.
+    fn draw(t: f64, a: f64, rng: &mut Pcg64) -> f64 {
+        if a <= 0.0 {
+            f64::INFINITY
+        } else {
+            let r: f64 = rng.sample(Exp1);
+            t + r / a
+        }
+    }
+
+    /// Returns the index of the reaction with the smallest putative
+    /// firing time, and that time, in O(1).
+    fn next(&self) -> (usize, f64) {
+        let mu = self.heap[0];
+        (mu, self.tau[mu])
+    }
+
+    /// Updates the putative firing times after reaction `mu` has fired
+    /// at time `t`, and repairs the heap for every reaction whose `tau`
+    /// changed. `propensity(j)` recomputes reaction `j`'s current
+    /// propensity; it is only called for `mu` and the reactions that
+    /// depend on it ([`DependencyGraph::graph`]), not every reaction,
+    /// since those are the only propensities `fire`ing `mu` can change.
+    fn update(&mut self, mu: usize, t: f64, propensity: impl Fn(usize) -> f64, rng: &mut Pcg64) {
+        let mut changed = Vec::with_capacity(1 + self.graph.graph[mu].len());
+        for j in std::iter::once(mu).chain(self.graph.graph[mu].iter().copied()) {
+            if changed.contains(&j) {
+                continue;
+            }
+            let a_old = self.a[j];
+            let a_new = propensity(j);
+            self.a[j] = a_new;
+            self.tau[j] = if j == mu || a_old <= 0.0 {
+                Self::draw(t, a_new, rng)
+            } else if a_new <= 0.0 {
+                f64::INFINITY
+            } else {
+                t + (a_old / a_new) * (self.tau[j] - t)
+            };
+            changed.push(j);
+        }
+        for reaction in changed {
+            self.fix(reaction);
+        }
+    }
+
+    /// Restores the heap property around `reaction`'s current position,
+    /// after its `tau` has changed in either direction.
+    fn fix(&mut self, reaction: usize) {
+        let pos = self.position[reaction];
+        self.sift_up(pos);
+        self.sift_down(self.position[reaction]);
+    }
+
+    /// Moves the entry at heap position `pos` up while it is smaller
+    /// than its parent.
+    fn sift_up(&mut self, mut pos: usize) {
+        while pos > 0 {
+            let parent = (pos - 1) / 2;
+            if self.tau[self.heap[pos]] < self.tau[self.heap[parent]] {
+                self.swap_heap(pos, parent);
+                pos = parent;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Moves the entry at heap position `pos` down while one of its
+    /// children is smaller.
+    fn sift_down(&mut self, mut pos: usize) {
+        let n = self.heap.len();
+        loop {
+            let left = 2 * pos + 1;
+            let right = 2 * pos + 2;
+            let mut smallest = pos;
+            if left < n && self.tau[self.heap[left]] < self.tau[self.heap[smallest]] {
+                smallest = left;
+            }
+            if right < n && self.tau[self.heap[right]] < self.tau[self.heap[smallest]] {
+                smallest = right;
+            }
+            if smallest == pos {
+                break;
+            }
+            self.swap_heap(pos, smallest);
+            pos = smallest;
+        }
+    }
+
+    /// Swaps the entries at heap positions `i` and `j`, keeping
+    /// `position` consistent with `heap`.
+    fn swap_heap(&mut self, i: usize, j: usize) {
+        self.heap.swap(i, j);
+        self.position[self.heap[i]] = i;
+        self.position[self.heap[j]] = j;
+    }
+}
+
+/// Derives a seed for trajectory `index` of an ensemble from a common
+/// `base_seed`, so that running the same ensemble twice with the same
+/// base seed reproduces the same (independent) trajectories regardless
+/// of the order or parallelism with which they are run.
+///
+/// This is a counter-based scheme: each index goes through its own
+/// [SplitMix64](https://prng.di.unimi.it/splitmix64.c) mixing step
+/// rather than being fed into a single shared stream, which is what
+/// makes the derived seeds usable as independent starting points.
+pub fn derive_seed(base_seed: u64, index: u64) -> u64 {
+    let mut z = base_seed.wrapping_add(index.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// A reaction network together with its current state, ready to be
+/// simulated.
+pub struct Gillespie {
+    t: f64,
+    x: Vec<isize>,
+    rates: Vec<Rate>,
+    actions: Vec<Vec<isize>>,
+    rng: Pcg64,
+    algorithm: Algorithm,
+    nrm: Option<NextReactionState>,
+}
+
+impl Gillespie {
+    /// Creates a new problem with the given initial populations,
+    /// seeded from entropy.
+    pub fn new(x0: impl Into<Vec<isize>>) -> Self {
+        Gillespie {
+            t: 0.,
+            x: x0.into(),
+            rates: Vec::new(),
+            actions: Vec::new(),
+            rng: Pcg64::from_entropy(),
+            algorithm: Algorithm::default(),
+            nrm: None,
+        }
+    }
+
+    /// Creates a new problem with the given initial populations, using
+    /// `seed` to initialize the random number generator, for
+    /// reproducibility.
+    pub fn new_with_seed(x0: impl Into<Vec<isize>>, seed: u64) -> Self {
+        Gillespie {
+            t: 0.,
+            x: x0.into(),
+            rates: Vec::new(),
+            actions: Vec::new(),
+            rng: Pcg64::seed_from_u64(seed),
+            algorithm: Algorithm::default(),
+            nrm: None,
+        }
+    }
+
+    /// Selects the SSA algorithm used by [`Gillespie::advance_until`].
+    pub fn set_algorithm(&mut self, algorithm: Algorithm) {
+        self.algorithm = algorithm;
+        self.nrm = None;
+    }
+
+    /// Adds a reaction with propensity `rate`, whose firing changes
+    /// the populations by `action`.
+    pub fn add_reaction(&mut self, rate: Rate, action: impl Into<Vec<isize>>) {
+        self.rates.push(rate);
+        self.actions.push(action.into());
+        self.nrm = None;
+    }
+
+    /// Returns the number of reactions in the system.
+    pub fn nb_reactions(&self) -> usize {
+        self.rates.len()
+    }
+
+    /// Returns the current simulation time.
+    pub fn get_time(&self) -> f64 {
+        self.t
+    }
+
+    /// Returns the current population of species `s`.
+    pub fn get_species(&self, s: usize) -> isize {
+        self.x[s]
+    }
+
+    /// Returns the current populations of all species.
+    pub fn get_state(&self) -> &[isize] {
+        &self.x
+    }
+
+    /// Returns the reactions' propensities, for use by other exact
+    /// methods built on top of the same reaction network (e.g.
+    /// [`crate::fsp`]).
+    pub(crate) fn rates(&self) -> &[Rate] {
+        &self.rates
+    }
+
+    /// Returns the reactions' stoichiometries, for use by other exact
+    /// methods built on top of the same reaction network (e.g.
+    /// [`crate::fsp`]).
+    pub(crate) fn actions(&self) -> &[Vec<isize>] {
+        &self.actions
+    }
+
+    /// Computes the propensities of all reactions into `rates` given
+    /// the current populations, at the current simulation time.
+    fn calculate_propensities(&self, rates: &mut [f64]) {
+        self.calculate_propensities_at(self.t, rates);
+    }
+
+    /// Computes the propensities of all reactions into `rates` given
+    /// the current populations, at time `t`.  Used by
+    /// [`Gillespie::_advance_one_reaction`] to evaluate a time-varying
+    /// rate at a candidate firing time that may differ from
+    /// [`Gillespie::get_time`].
+    fn calculate_propensities_at(&self, t: f64, rates: &mut [f64]) {
+        for (rate, r) in self.rates.iter().zip(rates.iter_mut()) {
+            *r = rate.evaluate(&self.x, t);
+        }
+    }
+
+    /// The next time strictly after the current simulation time at
+    /// which some reaction's propensity may jump discontinuously (a
+    /// [`Schedule::Piecewise`] breakpoint); `+inf` if none of the rates
+    /// vary piecewise.
+    fn next_rate_change(&self) -> f64 {
+        self.rates
+            .iter()
+            .filter_map(|r| r.next_breakpoint(self.t))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// A conservative upper bound on each reaction's propensity over
+    /// `[self.t, horizon]`, written into `rates`.
+    fn calculate_propensity_bounds(&self, horizon: f64, rates: &mut [f64]) {
+        for (rate, r) in self.rates.iter().zip(rates.iter_mut()) {
+            *r = rate.propensity_bound(&self.x, self.t, horizon);
+        }
+    }
+
+    /// Whether any reaction's rate depends on time, in which case a
+    /// zero propensity bound over a lookahead window does not prove
+    /// that the system is permanently quiescent (see
+    /// [`Gillespie::_advance_one_reaction`]).
+    fn has_time_varying_rate(&self) -> bool {
+        self.rates.iter().any(Rate::is_time_varying)
+    }
+
+    /// Applies the stoichiometry of reaction `mu` to the current
+    /// populations.
+    fn fire(&mut self, mu: usize) {
+        for (xi, &ai) in self.x.iter_mut().zip(self.actions[mu].iter()) {
+            *xi += ai;
+        }
+    }
+
+    /// Advances the simulation by exactly one reaction, using
+    /// Gillespie's direct method.  `rates` is a scratch buffer of
+    /// length [`Gillespie::nb_reactions`], reused across calls to
+    /// avoid reallocating.
+    ///
+    /// When some rate is time-varying, a single call to this method may
+    /// advance time without firing any reaction: for a
+    /// [`Schedule::Piecewise`] rate, each step is capped at the next
+    /// breakpoint, since the propensities only change there; for an
+    /// arbitrary continuous `f(t)`, a candidate firing time is drawn
+    /// against a local upper bound on the total propensity and accepted
+    /// or rejected by thinning, which keeps the exact SSA correct. When
+    /// no rate varies with time, this reduces exactly to the classic
+    /// direct method.
+    pub fn _advance_one_reaction(&mut self, rates: &mut [f64]) {
+        let next_change = self.next_rate_change();
+        let horizon = if next_change.is_finite() {
+            next_change
+        } else if self.has_time_varying_rate() {
+            self.t + THINNING_WINDOW
+        } else {
+            f64::INFINITY
+        };
+        self.calculate_propensity_bounds(horizon, rates);
+        let bound: f64 = rates.iter().sum();
+        if bound <= 0. {
+            // A zero bound over `[t, horizon]` only proves the system is
+            // permanently quiescent when no rate depends on time: with a
+            // time-varying rate, the propensity may still become positive
+            // beyond this lookahead window (e.g. an induction pulse), so
+            // advance the clock there and let the next call draw a fresh
+            // bound instead of ending the simulation early.
+            self.t = if next_change.is_finite() {
+                next_change
+            } else if self.has_time_varying_rate() {
+                horizon
+            } else {
+                f64::INFINITY
+            };
+            return;
+        }
+        let r1: f64 = self.rng.sample(Exp1);
+        let t_candidate = self.t + r1 / bound;
+        if t_candidate >= horizon {
+            // The bound is only valid up to `horizon`: advance the clock
+            // there without firing, and let the next call draw a fresh
+            // candidate from the propensities that hold beyond it.
+            self.t = horizon;
+            return;
+        }
+        self.calculate_propensities_at(t_candidate, rates);
+        let total: f64 = rates.iter().sum();
+        let r2: f64 = self.rng.sample(Uniform::new(0., bound));
+        self.t = t_candidate;
+        if r2 >= total {
+            // Thinning rejection: the actual propensity at `t_candidate`
+            // is below the bound used to draw it, so no reaction fires.
+            return;
+        }
+        let mut cumulative = 0.;
+        let mut mu = rates.len() - 1;
+        for (i, &ri) in rates.iter().enumerate() {
+            cumulative += ri;
+            if cumulative > r2 {
+                mu = i;
+                break;
+            }
+        }
+        self.fire(mu);
+    }
+
+    /// Advances the simulation by exactly one reaction, using the Next
+    /// Reaction Method.  The dependency graph and putative firing
+    /// times are built lazily on the first call and reused across
+    /// subsequent calls.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any rate is time-varying: the putative firing times
+    /// this method maintains are only ever rescaled linearly between
+    /// events (see [`NextReactionState::update`]), which silently skips
+    /// over breakpoints and pulses instead of respecting them.  Callers
+    /// that accept rates from an untrusted source (e.g. the Python
+    /// bindings) should reject this combination themselves with a
+    /// proper error before reaching this method.
+    fn advance_one_reaction_nrm(&mut self, rates: &mut [f64]) {
+        if self.nrm.is_none() {
+            assert!(
+                !self.has_time_varying_rate(),
+                "the Next Reaction Method does not support time-varying rates; use Algorithm::Direct instead"
+            );
+            self.calculate_propensities(rates);
+            let mut state = NextReactionState::new(&self.rates, &self.actions);
+            state.init(self.t, rates, &mut self.rng);
+            self.nrm = Some(state);
+        }
+        let (mu, tau_mu) = self.nrm.as_ref().unwrap().next();
+        if tau_mu.is_infinite() {
+            self.t = f64::INFINITY;
+            return;
+        }
+        self.t = tau_mu;
+        self.fire(mu);
+        let t = self.t;
+        let rates = &self.rates;
+        let x = &self.x;
+        let rng = &mut self.rng;
+        self.nrm
+            .as_mut()
+            .unwrap()
+            .update(mu, t, |j| rates[j].evaluate(x, t), rng);
+    }
+
+    /// Advances the simulation by exactly one reaction, using the
+    /// currently selected [`Algorithm`].  `rates` is a scratch buffer of
+    /// length [`Gillespie::nb_reactions`], reused across calls to avoid
+    /// reallocating.
+    pub fn advance_one_reaction(&mut self, rates: &mut [f64]) {
+        match self.algorithm {
+            Algorithm::Direct => self._advance_one_reaction(rates),
+            Algorithm::NextReaction => self.advance_one_reaction_nrm(rates),
+        }
+    }
+
+    /// Advances the simulation until time `tmax`, using the currently
+    /// selected [`Algorithm`].
+    pub fn advance_until(&mut self, tmax: f64) {
+        let n = self.nb_reactions();
+        let mut rates = vec![0.; n];
+        while self.t < tmax {
+            self.advance_one_reaction(&mut rates);
+        }
+        if self.t > tmax {
+            self.t = tmax;
+        }
+    }
+
+    /// Returns whether reaction `j` is critical: firing it fewer than
+    /// `n_c` times would exhaust one of its reactants.  Critical
+    /// reactions are excluded from leaping and fired exactly instead,
+    /// to avoid leaping a population negative.
+    fn is_critical(&self, j: usize, n_c: isize) -> bool {
+        self.x
+            .iter()
+            .zip(self.actions[j].iter())
+            .any(|(&xi, &aij)| aij < 0 && xi < n_c * (-aij))
+    }
+
+    /// Picks the largest leap size `tau` such that the expected
+    /// relative change of every species' population stays below
+    /// `epsilon`, following the standard tau-selection bound (Cao,
+    /// Gillespie and Petzold, 2006): for each species `s`, the mean and
+    /// variance of its net change per unit time are estimated from the
+    /// reactions' propensities `rates` and stoichiometries, and `tau` is
+    /// bounded so that the expected change in `s`'s own population
+    /// stays within `epsilon * max(x_s, 1)` (the `max` avoids forcing
+    /// `tau` to zero for a species that is currently absent). This
+    /// (unlike a bound scaled by total propensity `a0`, which has the
+    /// wrong units — population/time, not population — and no relation
+    /// to any single species' accuracy) is what keeps `epsilon` a
+    /// meaningful per-species relative-error target regardless of each
+    /// species' population scale.
+    fn select_tau(&self, rates: &[f64], epsilon: f64) -> f64 {
+        let mut tau = f64::INFINITY;
+        for s in 0..self.x.len() {
+            let mut mu = 0.;
+            let mut sigma2 = 0.;
+            for (j, &aj) in rates.iter().enumerate() {
+                let vjs = self.actions[j][s] as f64;
+                mu += vjs * aj;
+                sigma2 += vjs * vjs * aj;
+            }
+            let bound = epsilon * (self.x[s] as f64).max(1.);
+            if mu != 0. {
+                tau = tau.min(bound / mu.abs());
+            }
+            if sigma2 != 0. {
+                tau = tau.min(bound * bound / sigma2);
+            }
+        }
+        tau
+    }
+
+    /// Attempts a single tau-leap of size `tau`: draws a
+    /// Poisson-distributed number of firings for each non-critical
+    /// reaction (`critical[i]` is `false`) with mean `a_i(x) * tau` and
+    /// applies all the resulting stoichiometry changes at once, plus
+    /// exactly one firing of `critical_fire`, if given.  Returns `false`
+    /// without mutating the state if any species would become negative.
+    fn try_tau_leap(&mut self, tau: f64, critical: &[bool], critical_fire: Option<usize>) -> bool {
+        let n = self.nb_reactions();
+        let mut rates = vec![0.; n];
+        self.calculate_propensities(&mut rates);
+        let mut x_new = self.x.clone();
+        for (j, &a) in rates.iter().enumerate() {
+            if critical[j] || a <= 0. {
+                continue;
+            }
+            let firings = self.rng.sample(Poisson::new(a * tau).unwrap()) as isize;
+            if firings == 0 {
+                continue;
+            }
+            for (xi, &aij) in x_new.iter_mut().zip(self.actions[j].iter()) {
+                *xi += aij * firings;
+            }
+        }
+        if let Some(mu) = critical_fire {
+            for (xi, &aij) in x_new.iter_mut().zip(self.actions[mu].iter()) {
+                *xi += aij;
+            }
+        }
+        if x_new.iter().any(|&x| x < 0) {
+            return false;
+        }
+        self.x = x_new;
+        self.t += tau;
+        true
+    }
+
+    /// Advances the simulation by one explicit tau-leap of (approximate)
+    /// size `tau`.  If the leap would send a species negative, `tau` is
+    /// halved and the leap retried, up to [`TAU_LEAP_MAX_RETRIES`]
+    /// times, after which a single exact SSA step is taken instead.
+    /// Since `tau` is fixed by the caller, every reaction is leaped
+    /// (there is no critical-reaction separation here, unlike
+    /// [`Gillespie::_advance_one_tau_leap`]).
+    pub fn advance_tau_leap(&mut self, tau: f64) {
+        let not_critical = vec![false; self.nb_reactions()];
+        let mut tau = tau;
+        for _ in 0..TAU_LEAP_MAX_RETRIES {
+            if self.try_tau_leap(tau, &not_critical, None) {
+                return;
+            }
+            tau /= 2.;
+        }
+        let mut rates = vec![0.; self.nb_reactions()];
+        self._advance_one_reaction(&mut rates);
+    }
+
+    /// Advances the simulation by one step of automatic tau-leaping,
+    /// with critical-reaction separation (Cao, Gillespie and Petzold,
+    /// 2006): reactions within [`TAU_LEAP_N_C`] firings of exhausting a
+    /// reactant ([`Gillespie::is_critical`]) are excluded from the
+    /// Poisson leap and instead fired exactly at most once, via a
+    /// direct-method draw among them, so that they can never be
+    /// leaped past zero.  The leap size is the smaller of
+    /// [`Gillespie::select_tau`]'s bound from the non-critical
+    /// reactions and the waiting time to the next critical reaction;
+    /// if it is so small that fewer than one reaction is expected to
+    /// fire, a single exact SSA step is taken instead, which is both
+    /// correct and cheaper.
+    pub fn _advance_one_tau_leap(&mut self) {
+        let n = self.nb_reactions();
+        let mut rates = vec![0.; n];
+        self.calculate_propensities(&mut rates);
+        let a0: f64 = rates.iter().sum();
+        if a0 <= 0. {
+            self.t = f64::INFINITY;
+            return;
+        }
+        let critical: Vec<bool> = (0..n).map(|j| self.is_critical(j, TAU_LEAP_N_C)).collect();
+        let noncritical: Vec<f64> = (0..n)
+            .map(|j| if critical[j] { 0. } else { rates[j] })
+            .collect();
+        let tau1 = self.select_tau(&noncritical, TAU_LEAP_EPSILON);
+        let critical_a0: f64 = (0..n).filter(|&j| critical[j]).map(|j| rates[j]).sum();
+        let tau2 = if critical_a0 > 0. {
+            let r: f64 = self.rng.sample(Exp1);
+            r / critical_a0
+        } else {
+            f64::INFINITY
+        };
+        let tau = tau1.min(tau2);
+        if tau * a0 < 1. {
+            self._advance_one_reaction(&mut rates);
+            return;
+        }
+        let critical_fire = if tau2 <= tau1 && critical_a0 > 0. {
+            let r2: f64 = self.rng.sample(Uniform::new(0., critical_a0));
+            let mut cumulative = 0.;
+            (0..n).filter(|&j| critical[j]).find(|&j| {
+                cumulative += rates[j];
+                cumulative > r2
+            })
+        } else {
+            None
+        };
+        let mut tau = tau;
+        let mut fire = critical_fire;
+        for _ in 0..TAU_LEAP_MAX_RETRIES {
+            if self.try_tau_leap(tau, &critical, fire) {
+                return;
+            }
+            // A rejected leap drops the planned critical firing once `tau`
+            // is halved: committing to it again at a different `tau` would
+            // no longer match the waiting time it was drawn for.
+            tau /= 2.;
+            fire = None;
+        }
+        self._advance_one_reaction(&mut rates);
+    }
+
+    /// Advances the simulation until time `tmax` using automatic
+    /// tau-leaping, falling back to exact SSA steps whenever the
+    /// automatic step size would be too small to be worthwhile.
+    pub fn advance_until_tau_leap(&mut self, tmax: f64) {
+        while self.t < tmax {
+            self._advance_one_tau_leap();
+        }
+        if self.t > tmax {
+            self.t = tmax;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A simple linear birth-death system (birth at rate `birth`, death
+    /// at per-capita rate `death`), used to compare algorithms against
+    /// each other statistically.
+    fn birth_death(x0: isize, birth: f64, death: f64, seed: u64) -> Gillespie {
+        let mut g = Gillespie::new_with_seed([x0], seed);
+        g.add_reaction(Rate::lma(birth, [0]), [1]);
+        g.add_reaction(Rate::lma(death, [1]), [-1]);
+        g
+    }
+
+    /// The Next Reaction Method and the direct method are both exact
+    /// SSAs, so they must agree on the mean population of an ensemble of
+    /// trajectories, up to sampling noise.
+    #[test]
+    fn nrm_matches_direct_method_statistics() {
+        const N: u64 = 4000;
+        const TMAX: f64 = 5.0;
+        let mean = |algorithm: Algorithm| -> f64 {
+            let total: f64 = (0..N)
+                .map(|seed| {
+                    let mut g = birth_death(10, 2.0, 0.5, seed);
+                    g.set_algorithm(algorithm);
+                    g.advance_until(TMAX);
+                    g.get_species(0) as f64
+                })
+                .sum();
+            total / N as f64
+        };
+        let mean_direct = mean(Algorithm::Direct);
+        let mean_nrm = mean(Algorithm::NextReaction);
+        // Generous tolerance (many standard errors) to keep this test from
+        // being flaky while still catching a broken rescaling formula.
+        assert!(
+            (mean_direct - mean_nrm).abs() < 1.0,
+            "direct method mean {mean_direct} and NRM mean {mean_nrm} disagree"
+        );
+    }
+
+    /// Automatic tau-leaping must never send a population negative, even
+    /// started from a small count where naive leaping would overshoot.
+    #[test]
+    fn tau_leaping_never_goes_negative() {
+        for seed in 0..50 {
+            let mut g = birth_death(5, 1.0, 1.0, seed);
+            g.advance_until_tau_leap(20.0);
+            assert!(g.get_species(0) >= 0);
+        }
+    }
+
+    /// A reaction gated by a continuous schedule that is zero everywhere
+    /// except after a pulse at `t = 5` must still be able to fire after
+    /// the pulse: a zero propensity bound over one lookahead window must
+    /// not be mistaken for "quiescent forever".
+    #[test]
+    fn time_varying_schedule_respects_a_later_pulse() {
+        let schedule = Schedule::function(
+            |t: f64| if t < 5.0 { 0. } else { 10. },
+            |t: f64, window: f64| if t + window < 5.0 { 0. } else { 10. },
+        );
+        let mut g = Gillespie::new_with_seed([100], 0);
+        g.add_reaction(Rate::time_varying(schedule, Rate::lma(1.0, [1])), [-1]);
+        g.advance_until(6.0);
+        assert!(
+            g.get_species(0) < 100,
+            "no reaction fired after the pulse at t = 5"
+        );
+    }
+}
@@ -1,248 +1,280 @@
-//! rebop is a fast stochastic simulator for well-mixed chemical
-//! reaction networks.
-//!
-//! Performance and ergonomics are taken very seriously.  For this reason,
-//! two independent APIs are provided to describe and simulate reaction
-//! networks:
-//!
-//! * a macro-based DSL implemented by [`define_system`], usually the
-//! most efficient, but that requires to compile a rust program;
-//! * a function-based API implemented by the module [`gillespie`], also
-//! available through Python bindings.  This one does not require a rust
-//! compilation and allows the system to be defined at run time.  It is
-//! typically 2 or 3 times slower than the macro DSL, but still faster
-//! than all other software tried.
-//!
-//! # The macro DSL
-//!
-//! It currently only supports reaction rates defined by the law of mass
-//! action.  The following macro defines a dimerization reaction network
-//! naturally:
-//!
-//! ```rust
-//! use rebop::define_system;
-//! define_system! {
-//!     r_tx r_tl r_dim r_decay_mRNA r_decay_prot;
-//!     Dimers { gene, mRNA, protein, dimer }
-//!     transcription   : gene      => gene + mRNA      @ r_tx
-//!     translation     : mRNA      => mRNA + protein   @ r_tl
-//!     dimerization    : 2 protein => dimer            @ r_dim
-//!     decay_mRNA      : mRNA      =>                  @ r_decay_mRNA
-//!     decay_protein   : protein   =>                  @ r_decay_prot
-//! }
-//! ```
-//!
-//! To simulate the system, put this definition in a rust code file and
-//! instantiate the problem, set the parameters, the initial values, and
-//! launch the simulation:
-//!
-//! ```rust
-//! # use rebop::define_system;
-//! # define_system! {
-//! #     r_tx r_tl r_dim r_decay_mRNA r_decay_prot;
-//! #     Dimers { gene, mRNA, protein, dimer }
-//! #     transcription   : gene      => gene + mRNA      @ r_tx
-//! #     translation     : mRNA      => mRNA + protein   @ r_tl
-//! #     dimerization    : 2 protein => dimer            @ r_dim
-//! #     decay_mRNA      : mRNA      =>                  @ r_decay_mRNA
-//! #     decay_protein   : protein   =>                  @ r_decay_prot
-//! # }
-//! let mut problem = Dimers::new();
-//! problem.r_tx = 25.0;
-//! problem.r_tl = 1000.0;
-//! problem.r_dim = 0.001;
-//! problem.r_decay_mRNA = 0.1;
-//! problem.r_decay_prot = 1.0;
-//! problem.gene = 1;
-//! problem.advance_until(1.0);
-//! println!("t = {}: dimer = {}", problem.t, problem.dimer);
-//! ```
-//!
-//! Or for the classic SIR example:
-//!
-//! ```rust
-//! use rebop::define_system;
-//!
-//! define_system! {
-//!     r_inf r_heal;
-//!     SIR { S, I, R }
-//!     infection   : S + I => 2 I  @ r_inf
-//!     healing     : I     => R    @ r_heal
-//! }
-//!
-//! fn main() {
-//!     let mut problem = SIR::new();
-//!     problem.r_inf = 1e-4;
-//!     problem.r_heal = 0.01;
-//!     problem.S = 999;
-//!     problem.I = 1;
-//!     println!("time,S,I,R");
-//!     for t in 0..250 {
-//!         problem.advance_until(t as f64);
-//!         println!("{},{},{},{}", problem.t, problem.S, problem.I, problem.R);
-//!     }
-//! }
-//! ```
-//!
-//! which can produce an output similar to this one:
-//!
-//! ![Typical SIR output](https://github.com/Armavica/rebop/blob/main/sir.png?raw=true)
-//!
-//! # Python bindings
-//!
-//! This API shines through the Python bindings which allow one to
-//! define a model easily:
-//!
-//! ```python
-//! import rebop
-//!
-//! sir = rebop.Gillespie()
-//! sir.add_reaction(1e-4, ['S', 'I'], ['I', 'I'])
-//! sir.add_reaction(0.01, ['I'], ['R'])
-//! print(sir)
-//!
-//! ds = sir.run({'S': 999, 'I': 1}, tmax=250, nb_steps=250)
-//! ```
-//!
-//! You can test this code by installing `rebop` from PyPI with
-//! `pip install rebop`. To build the Python bindings from source,
-//! the simplest is to clone this git repository and use `maturin
-//! develop`.
-//!
-//! # The traditional API
-//!
-//! The function-based API underlying the Python package is also available
-//! from Rust, if you want to be able to define models at run time (instead
-//! of at compilation time with the macro DSL demonstrated above).
-//! The SIR model is defined as:
-//!
-//! ```rust
-//! use rebop::gillespie::{Gillespie, Rate};
-//!
-//! let mut sir = Gillespie::new([999, 1, 0]);
-//! //                           [  S, I, R]
-//! // S + I => 2 I with rate 1e-4
-//! sir.add_reaction(Rate::lma(1e-4, [1, 1, 0]), [-1, 1, 0]);
-//! // I => R with rate 0.01
-//! sir.add_reaction(Rate::lma(0.01, [0, 1, 0]), [0, -1, 1]);
-//!
-//! println!("time,S,I,R");
-//! for t in 0..250 {
-//!     sir.advance_until(t as f64);
-//!     println!("{},{},{},{}", sir.get_time(), sir.get_species(0), sir.get_species(1), sir.get_species(2));
-//! }
-//! ```
-//!
-//! # Performance
-//!
-//! Performance is taken very seriously, and as a result, rebop
-//! outperforms every other package and programming language that we
-//! tried.
-//!
-//! *Disclaimer*: Most of this software currently contains much more
-//! features than rebop (e.g. spatial models, custom reaction rates,
-//! etc.).  Some of these features might have required them to make
-//! compromises on speed.  Moreover, as much as we tried to keep the
-//! comparison fair, some return too much or too little data, or write
-//! them on disk.  The baseline that we tried to approach for all these
-//! programs is the following: *the model was just modified, we want
-//! to simulate it `N` times and print regularly spaced measurement
-//! points*.  This means that we always include initialization or
-//! (re-)compilation time if applicable.  We think that it is the most
-//! typical use-case of a researcher who works on the model.  This
-//! benchmark methods allows to record both the initialization time
-//! (y-intercept) and the simulation time per simulation (slope).
-//!
-//! Many small benchmarks on toy examples are tracked to guide the
-//! development.  To compare the performance with other software,
-//! we used a real-world model of low-medium size (9 species and 16
-//! reactions): the Vilar oscillator (*Mechanisms of noise-resistance
-//! in genetic oscillators*, Vilar et al., PNAS 2002).  Here, we
-//! simulate this model from `t=0` to `t=200`, reporting the state at
-//! time intervals of `1` time unit.
-//!
-//! ![Vilar oscillator benchmark](https://github.com/Armavica/rebop/blob/main/benches/vilar/vilar.png?raw=true)
-//!
-//! We can see that rebop's macro DSL is the fastest of all, both in
-//! time per simulation, and with compilation time included.  The second
-//! fastest is rebop's traditional API invoked by convenience through
-//! the Python bindings.
-//!
-//! # Features to come
-//!
-//! * compartment volumes
-//! * arbitrary reaction rates
-//! * other SSA algorithms
-//! * tau-leaping
-//! * adaptive tau-leaping
-//! * hybrid models (continuous and discrete)
-//! * SBML
-//! * CLI interface
-//! * parameter estimation
-//! * local sensitivity analysis
-//! * parallelization
-//!
-//! # Features probably not to come
-//!
-//! * events
-//! * space (reaction-diffusion systems)
-//! * rule modelling
-//!
-//! # Benchmark ideas
-//!
-//! * DSMTS
-//! * purely decoupled exponentials
-//! * ring
-//! * Toggle switch
-//! * LacZ, LacY/LacZ (from STOCKS)
-//! * Lotka Volterra, Michaelis--Menten, Network (from StochSim)
-//! * G protein (from SimBiology)
-//! * Brusselator / Oregonator (from Cellware)
-//! * GAL, repressilator (from Dizzy)
-//!
-//! # Similar software
-//!
-//! ## Maintained
-//!
-//! * [GillesPy2](https://github.com/StochSS/GillesPy2)
-//! * [STEPS](https://github.com/CNS-OIST/STEPS)
-//! * [SimBiology](https://fr.mathworks.com/help/simbio/)
-//! * [Copasi](http://copasi.org/)
-//! * [BioNetGen](http://bionetgen.org/)
-//! * [VCell](http://vcell.org/)
-//! * [Smoldyn](http://www.smoldyn.org/)
-//! * [KaSim](https://kappalanguage.org/)
-//! * [StochPy](https://github.com/SystemsBioinformatics/stochpy)
-//! * [BioSimulator.jl](https://github.com/alanderos91/BioSimulator.jl)
-//! * [DiffEqJump.jl](https://github.com/SciML/DiffEqJump.jl)
-//! * [Gillespie.jl](https://github.com/sdwfrost/Gillespie.jl)
-//! * [GillespieSSA2](https://github.com/rcannood/GillespieSSA2)
-//! * [Cayenne](https://github.com/quantumbrake/cayenne)
-//!
-//! ## Seem unmaintained
-//!
-//! * [Dizzy](http://magnet.systemsbiology.net/software/Dizzy/)
-//! * [Cellware](http://www.bii.a-star.edu.sg/achievements/applications/cellware/)
-//! * [STOCKS](https://doi.org/10.1093/bioinformatics/18.3.470)
-//! * [StochSim](http://lenoverelab.org/perso/lenov/stochsim.html)
-//! * [Systems biology toolbox](http://www.sbtoolbox.org/)
-//! * [StochKit](https://github.com/StochSS/StochKit) (successor: GillesPy2)
-//! * [SmartCell](http://software.crg.es/smartcell/)
-//! * [NFsim](http://michaelsneddon.net/nfsim/)
+//! Python bindings for rebop, exposing the function-based
+//! [`gillespie`](crate::gillespie) API as the `rebop.Gillespie` class.
 
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use rand::Rng;
+use rayon::prelude::*;
 use std::collections::HashMap;
-
-pub use rand;
-pub use rand_distr;
+use std::fmt;
 
 use crate::gillespie;
+use crate::gillespie::{Algorithm, Schedule};
+
+/// A reaction rate as specified from Python: either a plain law of mass
+/// action rate constant, or a (possibly nested) structured description
+/// of a non-mass-action term, built by [`parse_rate_term`].  Species are
+/// referenced by name, since at parsing time not all of them may have
+/// been assigned an index yet; they are resolved in
+/// [`Gillespie::build_gillespie`] once the whole system is known.
+#[derive(Debug, Clone)]
+enum RateTerm {
+    Lma(f64),
+    HillActivation {
+        k: f64,
+        species: String,
+        n: f64,
+        k_half: f64,
+    },
+    HillRepression {
+        k: f64,
+        species: String,
+        n: f64,
+        k_half: f64,
+    },
+    MichaelisMenten {
+        v: f64,
+        species: String,
+        k: f64,
+    },
+    Product(Vec<RateTerm>),
+    TimeVarying {
+        schedule: PySchedule,
+        factor: Box<RateTerm>,
+    },
+}
+
+/// A time-varying scalar, as specified from Python: either a
+/// `(value, bound)` pair of callables, or a piecewise-constant schedule
+/// of `(t, value)` breakpoints.  `bound(t, window)` must be a
+/// conservative upper bound on `value` over `[t, t + window]`; see
+/// [`gillespie::Schedule::Function`] for why a bare callable cannot be
+/// accepted on its own.  Resolved into a [`gillespie::Schedule`] in
+/// [`RateTerm::to_gillespie_rate`].
+#[derive(Debug, Clone)]
+enum PySchedule {
+    Callable { value: Py<PyAny>, bound: Py<PyAny> },
+    Piecewise(Vec<(f64, f64)>),
+}
+
+impl fmt::Display for RateTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateTerm::Lma(k) => write!(f, "{k}"),
+            RateTerm::HillActivation {
+                k,
+                species,
+                n,
+                k_half,
+            } => write!(f, "{k} * {species}^{n} / ({k_half}^{n} + {species}^{n})"),
+            RateTerm::HillRepression {
+                k,
+                species,
+                n,
+                k_half,
+            } => write!(f, "{k} * {k_half}^{n} / ({k_half}^{n} + {species}^{n})"),
+            RateTerm::MichaelisMenten { v, species, k } => {
+                write!(f, "{v} * {species} / ({k} + {species})")
+            }
+            RateTerm::Product(factors) => {
+                let terms: Vec<String> = factors.iter().map(RateTerm::to_string).collect();
+                write!(f, "{}", terms.join(" * "))
+            }
+            RateTerm::TimeVarying { schedule, factor } => match schedule {
+                PySchedule::Callable { .. } => write!(f, "f(t) * {factor}"),
+                PySchedule::Piecewise(_) => write!(f, "piecewise(t) * {factor}"),
+            },
+        }
+    }
+}
+
+impl RateTerm {
+    /// Names of the species that this term (recursively) depends on,
+    /// beyond those already implied by the reactants of the reaction.
+    fn species_names(&self) -> Vec<String> {
+        match self {
+            RateTerm::Lma(_) => Vec::new(),
+            RateTerm::HillActivation { species, .. }
+            | RateTerm::HillRepression { species, .. }
+            | RateTerm::MichaelisMenten { species, .. } => vec![species.clone()],
+            RateTerm::Product(factors) => {
+                factors.iter().flat_map(RateTerm::species_names).collect()
+            }
+            RateTerm::TimeVarying { factor, .. } => factor.species_names(),
+        }
+    }
+
+    /// Whether this term (recursively) depends on time.
+    fn is_time_varying(&self) -> bool {
+        match self {
+            RateTerm::Lma(_)
+            | RateTerm::HillActivation { .. }
+            | RateTerm::HillRepression { .. }
+            | RateTerm::MichaelisMenten { .. } => false,
+            RateTerm::Product(factors) => factors.iter().any(RateTerm::is_time_varying),
+            RateTerm::TimeVarying { .. } => true,
+        }
+    }
+
+    /// Builds the corresponding [`gillespie::Rate`], resolving species
+    /// names against `species` and using `vreactants` for the mass
+    /// action factors' stoichiometry.
+    fn to_gillespie_rate(
+        &self,
+        species: &HashMap<String, usize>,
+        vreactants: &[isize],
+    ) -> gillespie::Rate {
+        match self {
+            RateTerm::Lma(k) => gillespie::Rate::lma(*k, vreactants.to_vec()),
+            RateTerm::HillActivation {
+                k,
+                species: name,
+                n,
+                k_half,
+            } => gillespie::Rate::hill_activation(*k, species[name], *n, *k_half),
+            RateTerm::HillRepression {
+                k,
+                species: name,
+                n,
+                k_half,
+            } => gillespie::Rate::hill_repression(*k, species[name], *n, *k_half),
+            RateTerm::MichaelisMenten {
+                v,
+                species: name,
+                k,
+            } => gillespie::Rate::michaelis_menten(*v, species[name], *k),
+            RateTerm::Product(factors) => gillespie::Rate::product(
+                factors
+                    .iter()
+                    .map(|factor| factor.to_gillespie_rate(species, vreactants))
+                    .collect::<Vec<_>>(),
+            ),
+            RateTerm::TimeVarying { schedule, factor } => {
+                let schedule = match schedule {
+                    PySchedule::Piecewise(breakpoints) => Schedule::Piecewise(breakpoints.clone()),
+                    PySchedule::Callable { value, bound } => {
+                        let value = value.clone();
+                        let bound = bound.clone();
+                        // Neither callable can propagate a Python exception, since
+                        // `Rate::evaluate`/`Rate::propensity_bound` return a plain `f64`.
+                        // A raising `value` is treated as contributing no propensity;
+                        // a raising `bound` defaults to `+inf` instead, so a buggy bound
+                        // function fails safe (over-thinning) rather than silently
+                        // breaking exactness.
+                        Schedule::function(
+                            move |t: f64| {
+                                Python::with_gil(|py| {
+                                    value
+                                        .call1(py, (t,))
+                                        .and_then(|v| v.extract::<f64>(py))
+                                        .unwrap_or(0.)
+                                })
+                            },
+                            move |t: f64, window: f64| {
+                                Python::with_gil(|py| {
+                                    bound
+                                        .call1(py, (t, window))
+                                        .and_then(|v| v.extract::<f64>(py))
+                                        .unwrap_or(f64::INFINITY)
+                                })
+                            },
+                        )
+                    }
+                };
+                gillespie::Rate::time_varying(
+                    schedule,
+                    factor.to_gillespie_rate(species, vreactants),
+                )
+            }
+        }
+    }
+}
+
+/// Parses a rate specification passed from Python into a [`RateTerm`]: a
+/// plain number for a law of mass action, a dict describing a single
+/// non-mass-action term (with a `"type"` key of `"lma"`,
+/// `"hill_activation"`, `"hill_repression"`, `"mm"` or `"time_varying"`),
+/// or a list of such terms to be multiplied together.
+fn parse_rate_term(obj: &Bound<'_, PyAny>) -> PyResult<RateTerm> {
+    if let Ok(k) = obj.extract::<f64>() {
+        return Ok(RateTerm::Lma(k));
+    }
+    if let Ok(list) = obj.downcast::<PyList>() {
+        let factors = list
+            .iter()
+            .map(|item| parse_rate_term(&item))
+            .collect::<PyResult<Vec<_>>>()?;
+        return Ok(RateTerm::Product(factors));
+    }
+    let dict = obj.downcast::<PyDict>().map_err(|_| {
+        PyValueError::new_err(
+            "rate must be a float, a dict describing a rate term, or a list of such terms",
+        )
+    })?;
+    let field = |key: &str| -> PyResult<Bound<'_, PyAny>> {
+        dict.get_item(key)?
+            .ok_or_else(|| PyValueError::new_err(format!("rate term is missing {key:?}")))
+    };
+    let field_f64 = |key: &str| -> PyResult<f64> { field(key)?.extract() };
+    let field_str = |key: &str| -> PyResult<String> { field(key)?.extract() };
+    match field_str("type")?.as_str() {
+        "lma" => Ok(RateTerm::Lma(field_f64("k")?)),
+        "hill_activation" => Ok(RateTerm::HillActivation {
+            k: field_f64("k")?,
+            species: field_str("species")?,
+            n: field_f64("n")?,
+            k_half: field_f64("K")?,
+        }),
+        "hill_repression" => Ok(RateTerm::HillRepression {
+            k: field_f64("k")?,
+            species: field_str("species")?,
+            n: field_f64("n")?,
+            k_half: field_f64("K")?,
+        }),
+        "mm" => Ok(RateTerm::MichaelisMenten {
+            v: field_f64("v")?,
+            species: field_str("species")?,
+            k: field_f64("K")?,
+        }),
+        "time_varying" => Ok(RateTerm::TimeVarying {
+            schedule: parse_schedule(&field("schedule")?)?,
+            factor: Box::new(parse_rate_term(&field("factor")?)?),
+        }),
+        other => Err(PyValueError::new_err(format!(
+            "unknown rate term type {other:?}, expected \"lma\", \"hill_activation\", \"hill_repression\", \"mm\" or \"time_varying\""
+        ))),
+    }
+}
+
+/// Parses the `"schedule"` field of a `"time_varying"` rate term: either
+/// a `(value, bound)` pair of callables, or a list of `(t, value)`
+/// breakpoints for a piecewise-constant schedule.  `bound(t, window)`
+/// must return a conservative upper bound on `value(s)` for `s` in
+/// `[t, t + window]`; a bare callable is rejected rather than silently
+/// sampled, since sampling cannot catch a transient narrower than the
+/// sampling gap (see [`gillespie::Schedule::Function`]).
+fn parse_schedule(obj: &Bound<'_, PyAny>) -> PyResult<PySchedule> {
+    if let Ok(breakpoints) = obj.extract::<Vec<(f64, f64)>>() {
+        return Ok(PySchedule::Piecewise(breakpoints));
+    }
+    if let Ok((value, bound)) = obj.extract::<(Py<PyAny>, Py<PyAny>)>() {
+        if value.bind(obj.py()).is_callable() && bound.bind(obj.py()).is_callable() {
+            return Ok(PySchedule::Callable { value, bound });
+        }
+    }
+    Err(PyValueError::new_err(
+        "schedule must be a list of (t, value) breakpoints, or a (f, bound) pair of callables \
+         where bound(t, window) is a conservative upper bound on f over [t, t + window] \
+         (a single callable cannot be bounded safely for exact thinning)",
+    ))
+}
 
 /// Reaction system composed of species and reactions.
 #[pyclass]
 struct Gillespie {
     species: HashMap<String, usize>,
-    reactions: Vec<(f64, Vec<String>, Vec<String>)>,
+    reactions: Vec<(RateTerm, Vec<String>, Vec<String>)>,
 }
 
 #[pymethods]
@@ -258,19 +290,32 @@ impl Gillespie {
     fn nb_species(&self) -> PyResult<usize> {
         Ok(self.species.len())
     }
-    /// Add a Law of Mass Action reaction to the system.
+    /// Add a reaction to the system.
     ///
-    /// The forward reaction rate is `rate`, while `reactants` and `products` are lists of
-    /// respectively reactant names and product names.  Add the reverse reaction with the rate
+    /// `rate` is either a plain number, for a law of mass action reaction, or a structured
+    /// description of an arbitrary propensity term: a dict with a `"type"` key of `"lma"`,
+    /// `"hill_activation"`, `"hill_repression"`, `"mm"` (Michaelis-Menten) or `"time_varying"`,
+    /// or a list of such terms to be multiplied together (e.g. a mass action reaction modulated
+    /// by a Hill term).  Hill and Michaelis-Menten terms take a `"species"` key naming the
+    /// species they depend on, which need not be a reactant of the reaction.  A `"time_varying"`
+    /// term takes a `"factor"` key (any rate term, including a plain number) and a `"schedule"`
+    /// key: either a `(f, bound)` pair of callables, where `bound(t, window)` is a conservative
+    /// upper bound on `f` over `[t, t + window]` (a bare callable cannot be bounded safely for
+    /// exact thinning and is rejected), or a list of `(t, value)` breakpoints for a
+    /// piecewise-constant schedule, holding `value` from each breakpoint until the next (and
+    /// `0` before the first one) — useful for induction pulses, temperature shifts, or other
+    /// externally driven inputs.  `reactants` and `products` are lists of respectively reactant
+    /// names and product names.  Add the reverse reaction with the law of mass action rate
     /// `reverse_rate` if it is not `None`.
     #[pyo3(signature = (rate, reactants, products, reverse_rate=None))]
     fn add_reaction(
         &mut self,
-        rate: f64,
+        rate: &Bound<'_, PyAny>,
         reactants: Vec<String>,
         products: Vec<String>,
         reverse_rate: Option<f64>,
     ) -> PyResult<()> {
+        let rate = parse_rate_term(rate)?;
         // Insert unknown reactants in known species
         for reactant in &reactants {
             if !self.species.contains_key(reactant) {
@@ -283,10 +328,17 @@ impl Gillespie {
                 self.species.insert(product.clone(), self.species.len());
             }
         }
+        // Insert species referenced only by the rate (e.g. a Hill regulator)
+        for name in rate.species_names() {
+            if !self.species.contains_key(&name) {
+                self.species.insert(name, self.species.len());
+            }
+        }
         self.reactions
             .push((rate, reactants.clone(), products.clone()));
         if let Some(rrate) = reverse_rate {
-            self.reactions.push((rrate, products, reactants));
+            self.reactions
+                .push((RateTerm::Lma(rrate), products, reactants));
         }
         Ok(())
     }
@@ -302,40 +354,41 @@ impl Gillespie {
     /// values at the given time points.  One can specify a random `seed` for reproducibility.
     /// If `nb_steps` is `0`, then returns all reactions, ending with the first that happens at
     /// or after `tmax`.
-    #[pyo3(signature = (init, tmax, nb_steps, seed=None))]
+    ///
+    /// `algorithm` selects the exact SSA engine: `"direct"` (the default) for Gillespie's direct
+    /// method, or `"next_reaction"` for the Next Reaction Method (Gibson and Bruck), which is
+    /// usually faster on large, sparsely coupled reaction networks.  It is ignored when
+    /// `method` is `"tau_leaping"`.  `"next_reaction"` does not support `"time_varying"` rates
+    /// and raises a `ValueError` if the system has any; use `"direct"` for those instead.
+    ///
+    /// `method` selects between exact simulation (`"ssa"`, the default) and approximate
+    /// tau-leaping (`"tau_leaping"`), which is much faster on systems with large populations at
+    /// the cost of some accuracy.  With `"tau_leaping"`, `tau` fixes the leap size; if `tau` is
+    /// `None`, the leap size is chosen automatically at each step.
+    ///
+    /// With `nb_steps` equal to `0`, tau-leaping reports one entry per leap rather than per
+    /// reaction, and with `"time_varying"` rates, steps in which no reaction fires (a thinning
+    /// rejection, or reaching a breakpoint) are reported too.
+    #[pyo3(signature = (init, tmax, nb_steps, seed=None, algorithm="direct", method="ssa", tau=None))]
+    #[allow(clippy::too_many_arguments)]
     fn run(
         &self,
         init: HashMap<String, usize>,
         tmax: f64,
         nb_steps: usize,
         seed: Option<u64>,
+        algorithm: &str,
+        method: &str,
+        tau: Option<f64>,
     ) -> PyResult<(Vec<f64>, HashMap<String, Vec<isize>>)> {
-        let mut x0 = vec![0; self.species.len()];
-        for (name, &value) in &init {
-            if let Some(&id) = self.species.get(name) {
-                x0[id] = value as isize;
-            }
-        }
-        let mut g = match seed {
-            Some(seed) => gillespie::Gillespie::new_with_seed(x0, seed),
-            None => gillespie::Gillespie::new(x0),
-        };
-
-        for (rate, reactants, products) in self.reactions.iter() {
-            let mut vreactants = vec![0; self.species.len()];
-            for reactant in reactants {
-                vreactants[self.species[reactant]] += 1;
-            }
-            let rate = gillespie::Rate::lma(*rate, vreactants);
-            let mut actions = vec![0; self.species.len()];
-            for reactant in reactants {
-                actions[self.species[reactant]] -= 1;
-            }
-            for product in products {
-                actions[self.species[product]] += 1;
-            }
-            g.add_reaction(rate, actions);
+        let algorithm = Self::parse_algorithm(algorithm)?;
+        let tau_leaping = Self::parse_method(method)?;
+        if !tau_leaping {
+            self.check_algorithm_supports_rates(algorithm)?;
         }
+        Self::validate_tau(tau)?;
+        let x0 = self.x0_from_init(&init);
+        let mut g = self.build_gillespie(x0, seed, algorithm);
         let mut times = Vec::new();
         // species.shape = (species, nb_steps)
         let mut species = vec![Vec::new(); self.species.len()];
@@ -343,7 +396,7 @@ impl Gillespie {
             for i in 0..=nb_steps {
                 let t = tmax * i as f64 / nb_steps as f64;
                 times.push(t);
-                g.advance_until(t);
+                Self::advance_to(&mut g, t, tau_leaping, tau);
                 for s in 0..self.species.len() {
                     species[s].push(g.get_species(s));
                 }
@@ -356,7 +409,14 @@ impl Gillespie {
                 species[s].push(g.get_species(s));
             }
             while g.get_time() < tmax {
-                g._advance_one_reaction(&mut rates);
+                if tau_leaping {
+                    match tau {
+                        Some(tau) => g.advance_tau_leap(tau),
+                        None => g._advance_one_tau_leap(),
+                    }
+                } else {
+                    g.advance_one_reaction(&mut rates);
+                }
                 times.push(g.get_time());
                 for s in 0..self.species.len() {
                     species[s].push(g.get_species(s));
@@ -369,6 +429,79 @@ impl Gillespie {
         }
         Ok((times, result))
     }
+    /// Run `n_traj` independent realizations of the system, in parallel.
+    ///
+    /// Arguments are the same as [`Gillespie::run`], with `nb_steps` required to be positive
+    /// since all trajectories are reported on the same uniformly spaced time grid.  Each
+    /// trajectory `i` is seeded deterministically from `seed` (or from entropy if `seed` is
+    /// `None`) so that re-running the ensemble, in any order or degree of parallelism, always
+    /// reproduces the same `n_traj` trajectories.
+    ///
+    /// Returns `times, vars` where `vars` maps each species name to a list of `n_traj`
+    /// trajectories, each of `nb_steps + 1` values.
+    #[pyo3(signature = (init, tmax, nb_steps, n_traj, seed=None, algorithm="direct", method="ssa", tau=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn run_ensemble(
+        &self,
+        py: Python<'_>,
+        init: HashMap<String, usize>,
+        tmax: f64,
+        nb_steps: usize,
+        n_traj: usize,
+        seed: Option<u64>,
+        algorithm: &str,
+        method: &str,
+        tau: Option<f64>,
+    ) -> PyResult<(Vec<f64>, HashMap<String, Vec<Vec<isize>>>)> {
+        if nb_steps == 0 {
+            return Err(PyValueError::new_err(
+                "run_ensemble requires nb_steps > 0 so that all trajectories share a common time grid",
+            ));
+        }
+        let algorithm = Self::parse_algorithm(algorithm)?;
+        let tau_leaping = Self::parse_method(method)?;
+        if !tau_leaping {
+            self.check_algorithm_supports_rates(algorithm)?;
+        }
+        Self::validate_tau(tau)?;
+        let base_seed = seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let x0 = self.x0_from_init(&init);
+        let times: Vec<f64> = (0..=nb_steps)
+            .map(|i| tmax * i as f64 / nb_steps as f64)
+            .collect();
+
+        // species.shape = (species, n_traj, nb_steps + 1)
+        // Releases the GIL for the duration of the parallel section: a
+        // `"time_varying"` rate backed by a Python callable re-acquires it
+        // (via `Python::with_gil`) from each rayon worker thread, which
+        // would otherwise deadlock against this thread holding it here.
+        let trajectories: Vec<Vec<Vec<isize>>> = py.allow_threads(|| {
+            (0..n_traj)
+                .into_par_iter()
+                .map(|i| {
+                    let seed_i = gillespie::derive_seed(base_seed, i as u64);
+                    let mut g = self.build_gillespie(x0.clone(), Some(seed_i), algorithm);
+                    let mut species = vec![Vec::with_capacity(nb_steps + 1); self.species.len()];
+                    for &t in &times {
+                        Self::advance_to(&mut g, t, tau_leaping, tau);
+                        for s in 0..self.species.len() {
+                            species[s].push(g.get_species(s));
+                        }
+                    }
+                    species
+                })
+                .collect()
+        });
+
+        let mut result = HashMap::new();
+        for (name, &id) in &self.species {
+            result.insert(
+                name.clone(),
+                trajectories.iter().map(|traj| traj[id].clone()).collect(),
+            );
+        }
+        Ok((times, result))
+    }
     fn __str__(&self) -> PyResult<String> {
         let mut s = format!(
             "{} species and {} reactions\n",
@@ -385,6 +518,123 @@ impl Gillespie {
     }
 }
 
+impl Gillespie {
+    /// Parses the `algorithm` argument of [`Gillespie::run`].
+    fn parse_algorithm(algorithm: &str) -> PyResult<Algorithm> {
+        match algorithm {
+            "direct" => Ok(Algorithm::Direct),
+            "next_reaction" => Ok(Algorithm::NextReaction),
+            other => Err(PyValueError::new_err(format!(
+                "unknown algorithm {other:?}, expected \"direct\" or \"next_reaction\""
+            ))),
+        }
+    }
+    /// Parses the `method` argument of [`Gillespie::run`], returning whether tau-leaping was
+    /// requested.
+    fn parse_method(method: &str) -> PyResult<bool> {
+        match method {
+            "ssa" => Ok(false),
+            "tau_leaping" => Ok(true),
+            other => Err(PyValueError::new_err(format!(
+                "unknown method {other:?}, expected \"ssa\" or \"tau_leaping\""
+            ))),
+        }
+    }
+    /// Validates the `tau` argument of [`Gillespie::run`]: a fixed leap size of `0` or less
+    /// would either never advance time (an infinite loop in [`Gillespie::advance_to`]) or send
+    /// [`gillespie::Gillespie::advance_tau_leap`]'s `Poisson::new` an invalid (non-positive)
+    /// mean.
+    fn validate_tau(tau: Option<f64>) -> PyResult<()> {
+        if let Some(tau) = tau {
+            if !(tau > 0.) {
+                return Err(PyValueError::new_err(format!(
+                    "tau must be strictly positive, got {tau}"
+                )));
+            }
+        }
+        Ok(())
+    }
+    /// Rejects `algorithm=\"next_reaction\"` if any reaction's rate is time-varying: the Next
+    /// Reaction Method only ever rescales its putative firing times linearly between events,
+    /// which silently skips over breakpoints and pulses instead of respecting them.
+    fn check_algorithm_supports_rates(&self, algorithm: Algorithm) -> PyResult<()> {
+        if algorithm == Algorithm::NextReaction
+            && self
+                .reactions
+                .iter()
+                .any(|(rate, ..)| rate.is_time_varying())
+        {
+            return Err(PyValueError::new_err(
+                "algorithm=\"next_reaction\" does not support time-varying (\"time_varying\") rates; use algorithm=\"direct\" instead",
+            ));
+        }
+        Ok(())
+    }
+    /// Converts the `init` dictionary into the dense initial population vector expected by
+    /// [`gillespie::Gillespie`].
+    fn x0_from_init(&self, init: &HashMap<String, usize>) -> Vec<isize> {
+        let mut x0 = vec![0; self.species.len()];
+        for (name, &value) in init {
+            if let Some(&id) = self.species.get(name) {
+                x0[id] = value as isize;
+            }
+        }
+        x0
+    }
+    /// Builds a fresh [`gillespie::Gillespie`] from the reactions and species of this object.
+    fn build_gillespie(
+        &self,
+        x0: Vec<isize>,
+        seed: Option<u64>,
+        algorithm: Algorithm,
+    ) -> gillespie::Gillespie {
+        let mut g = match seed {
+            Some(seed) => gillespie::Gillespie::new_with_seed(x0, seed),
+            None => gillespie::Gillespie::new(x0),
+        };
+        g.set_algorithm(algorithm);
+        for (rate, reactants, products) in self.reactions.iter() {
+            let mut vreactants = vec![0; self.species.len()];
+            for reactant in reactants {
+                vreactants[self.species[reactant]] += 1;
+            }
+            let rate = rate.to_gillespie_rate(&self.species, &vreactants);
+            let mut actions = vec![0; self.species.len()];
+            for reactant in reactants {
+                actions[self.species[reactant]] -= 1;
+            }
+            for product in products {
+                actions[self.species[product]] += 1;
+            }
+            g.add_reaction(rate, actions);
+        }
+        g
+    }
+    /// Advances `g` to time `t`, using exact SSA or tau-leaping (fixed-size if `tau` is
+    /// `Some`, automatic otherwise) according to `tau_leaping`.
+    fn advance_to(g: &mut gillespie::Gillespie, t: f64, tau_leaping: bool, tau: Option<f64>) {
+        if tau_leaping {
+            match tau {
+                Some(tau) => {
+                    while g.get_time() + tau <= t {
+                        g.advance_tau_leap(tau);
+                    }
+                    // Close the remaining gap with one last, shorter leap
+                    // instead of leaving the reported state up to `tau`
+                    // behind the requested time grid.
+                    let remaining = t - g.get_time();
+                    if remaining > 0. {
+                        g.advance_tau_leap(remaining);
+                    }
+                }
+                None => g.advance_until_tau_leap(t),
+            }
+        } else {
+            g.advance_until(t);
+        }
+    }
+}
+
 #[pymodule]
 fn rebop(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add("__version__", env!("CARGO_PKG_VERSION"))?;